@@ -1,41 +1,100 @@
 use std::{
     collections::{HashMap, HashSet},
     fmt::{self, Display},
+    path::Path,
 };
 
 use clap::{App, AppSettings, Arg, ArgMatches};
 use itertools::Itertools;
 use parser::Span;
-use thiserror::Error;
 
 mod parser;
 
 #[derive(Debug)]
 pub struct Script<'a> {
     items: Vec<Item<'a>>,
+    aliases: HashMap<&'a str, Vec<String>>,
+    alias_abouts: HashMap<&'a str, String>,
     only_pub_main_index: Option<usize>,
 }
 
 impl<'a> Script<'a> {
     pub fn parse(input: &'a str) -> Result<Self, ParseError> {
-        let items = parser::parse(input)?;
+        let (items, alias_decls) = parser::parse(input)?;
         let mut names = HashSet::new();
+        let mut pub_names = HashSet::new();
         let mut only_pub_main_index = None;
+        let mut pub_main_index = None;
         let mut pub_count = 0;
 
         for (index, item) in items.iter().enumerate() {
             let name = item.fn_signature.name;
+            let name_span = item.fn_signature.name_span;
+
+            if !names.insert(name) {
+                return Err(ParseError::at(
+                    input,
+                    name_span,
+                    format!("duplicate function name `{}`", name),
+                ));
+            }
+
+            let args = &item.fn_signature.args;
+            let variadic_position = args
+                .iter()
+                .position(|arg| matches!(arg.kind, ArgKind::Positional(Arity::Variadic)));
+
+            if let Some(position) = variadic_position {
+                if position != args.len() - 1 {
+                    return Err(ParseError::at(
+                        input,
+                        name_span,
+                        format!("only the last parameter of `{}` may be variadic", name),
+                    ));
+                }
+            }
 
-            assert!(names.insert(name));
+            let mut seen_optional = false;
+
+            for arg in args {
+                if let ArgKind::Positional(arity) = &arg.kind {
+                    match arity {
+                        Arity::Optional { .. } => seen_optional = true,
+                        Arity::Required if seen_optional => {
+                            return Err(ParseError::at(
+                                input,
+                                name_span,
+                                format!(
+                                    "required parameter `{}` of `{}` may not follow an optional parameter",
+                                    arg.name, name
+                                ),
+                            ));
+                        }
+                        Arity::Required | Arity::Variadic => {}
+                    }
+                }
+            }
 
             let is_pub = item.is_pub;
 
             if is_pub {
                 pub_count += 1;
+                pub_names.insert(name);
             }
 
             if is_pub && name == "main" {
                 only_pub_main_index = Some(index);
+                pub_main_index = Some(index);
+            }
+        }
+
+        if let Some(index) = pub_main_index {
+            if pub_count != 1 {
+                return Err(ParseError::at(
+                    input,
+                    items[index].fn_signature.name_span,
+                    "when a script defines `pub fn main`, it must be the only public function",
+                ));
             }
         }
 
@@ -43,8 +102,26 @@ impl<'a> Script<'a> {
             only_pub_main_index = None;
         }
 
+        if only_pub_main_index.is_some() {
+            if let Some(first_alias) = alias_decls.first() {
+                return Err(ParseError::at(
+                    input,
+                    first_alias.name_span,
+                    "aliases aren't reachable when the script has a single `pub fn main`",
+                ));
+            }
+        }
+
+        let aliases = validate_aliases(input, alias_decls, &names, &pub_names)?;
+        let alias_abouts = aliases
+            .iter()
+            .map(|(name, expansion)| (*name, format!("Alias for `{}`", expansion[0])))
+            .collect();
+
         Ok(Self {
             items,
+            aliases,
+            alias_abouts,
             only_pub_main_index,
         })
     }
@@ -78,6 +155,11 @@ impl<'a> Script<'a> {
             }
         }
 
+        for alias in self.aliases.keys() {
+            app = app.subcommand(App::new(*alias).about(self.alias_abouts[alias].as_str()));
+        }
+
+        let args = rewrite_aliases(&self.aliases, args);
         let arg_matches = app.get_matches_from(args);
         let (name, subcmd_matches) = arg_matches.subcommand().unwrap();
 
@@ -111,11 +193,92 @@ impl<'a> Script<'a> {
     }
 }
 
+/// Checks the alias declarations the parser collected against each other and
+/// against the script's function names before they're trusted as a
+/// `name -> expansion` map: an alias name must be unique, must not shadow a
+/// real function (both would otherwise register the same clap subcommand
+/// name twice), and must expand to a function that actually exists and is
+/// reachable as a subcommand.
+fn validate_aliases<'a>(
+    source: &str,
+    alias_decls: Vec<parser::AliasDecl<'a>>,
+    fn_names: &HashSet<&'a str>,
+    pub_fn_names: &HashSet<&'a str>,
+) -> Result<HashMap<&'a str, Vec<String>>, ParseError> {
+    let mut alias_names = HashSet::new();
+    let mut aliases = HashMap::new();
+
+    for decl in alias_decls {
+        if fn_names.contains(decl.name) {
+            return Err(ParseError::at(
+                source,
+                decl.name_span,
+                format!(
+                    "alias name `{}` collides with an existing function",
+                    decl.name
+                ),
+            ));
+        }
+
+        if !alias_names.insert(decl.name) {
+            return Err(ParseError::at(
+                source,
+                decl.name_span,
+                format!("duplicate alias name `{}`", decl.name),
+            ));
+        }
+
+        let target = &decl.expansion[0];
+
+        if !pub_fn_names.contains(target.as_str()) {
+            return Err(ParseError::at(
+                source,
+                decl.target_span,
+                format!("alias target `{}` is not a known public function", target),
+            ));
+        }
+
+        aliases.insert(decl.name, decl.expansion);
+    }
+
+    Ok(aliases)
+}
+
+/// Rewrites the subcommand position of `args` from an alias name to its
+/// expansion (target function name followed by its preset arguments) before
+/// handing the args to clap, so the alias never has to be a real subcommand.
+fn rewrite_aliases(
+    aliases: &HashMap<&str, Vec<String>>,
+    args: impl IntoIterator<Item = String>,
+) -> Vec<String> {
+    let mut args = args.into_iter();
+    let mut rewritten = vec![args.next().unwrap_or_default()];
+
+    // Global flags such as `--debug` may come before the subcommand.
+    for arg in args.by_ref() {
+        if arg.starts_with('-') {
+            rewritten.push(arg);
+            continue;
+        }
+
+        match aliases.get(arg.as_str()) {
+            Some(expansion) => rewritten.extend(expansion.iter().cloned()),
+            None => rewritten.push(arg),
+        }
+
+        break;
+    }
+
+    rewritten.extend(args);
+    rewritten
+}
+
 const DEBUG_FLAG: &str = "debug";
 
 pub struct FnCall {
     pub name: String,
     pub args: Vec<String>,
+    pub env: HashMap<String, String>,
     pub debug: bool,
 }
 
@@ -124,42 +287,161 @@ impl FnCall {
         name: &str,
         arg_matches: &ArgMatches,
         subcmd_matches: &ArgMatches,
-        arg_names: Vec<&str>,
+        arg_names: Vec<&ItemArg>,
     ) -> Self {
+        let (args, env) = extract_args(subcmd_matches, arg_names);
+
         Self {
             name: name.to_owned(),
-            args: extract_args(subcmd_matches, arg_names),
+            args,
+            env,
             debug: arg_matches.is_present(DEBUG_FLAG),
         }
     }
 }
 
-fn item_arg_spec<'a>(mut app: App<'a>, item: &'a Item) -> (App<'a>, Vec<&'a str>) {
+fn item_arg_spec<'a>(mut app: App<'a>, item: &'a Item) -> (App<'a>, Vec<&'a ItemArg<'a>>) {
     let mut arg_names = Vec::new();
+    let fn_name = item.fn_signature.name;
 
     for item_arg in &item.fn_signature.args {
-        let mut arg = Arg::new(item_arg.name)
-            .required(true)
-            .multiple_values(false);
-        arg = arg.help(&item_arg.description);
+        let mut arg = Arg::new(item_arg.name).help(&item_arg.description);
+
+        arg = match &item_arg.kind {
+            ArgKind::Positional(Arity::Variadic) => arg.required(false).multiple_values(true),
+            ArgKind::Positional(arity) => arg
+                .required(matches!(arity, Arity::Required))
+                .multiple_values(false),
+            ArgKind::Flag => arg.long(item_arg.name).takes_value(false),
+            ArgKind::Option => arg.long(item_arg.name).takes_value(true).required(false),
+        };
+
+        if !matches!(item_arg.kind, ArgKind::Flag) {
+            arg = apply_arg_type(arg, fn_name, item_arg.name, &item_arg.ty);
+        }
+
         app = app.arg(arg);
-        arg_names.push(item_arg.name);
+        arg_names.push(item_arg);
     }
 
     (app, arg_names)
 }
 
-fn extract_args(arg_matches: &ArgMatches, item_args: Vec<&str>) -> Vec<String> {
-    item_args
-        .into_iter()
-        .map(|item_arg| {
-            let mut values = arg_matches.values_of(item_arg).unwrap();
-            let value = values.next().unwrap();
-            assert!(values.next().is_none());
+fn apply_arg_type<'a>(
+    arg: Arg<'a>,
+    fn_name: &'a str,
+    arg_name: &'a str,
+    ty: &'a ArgType,
+) -> Arg<'a> {
+    if let ArgType::Choice(choices) = ty {
+        return arg.possible_values(choices.iter().map(String::as_str));
+    }
 
-            value.to_owned()
-        })
-        .collect()
+    let ty = ty.clone();
+
+    arg.validator(move |value| validate_arg_value(fn_name, arg_name, &ty, value))
+}
+
+/// clap's own `io::Error`-derived messages for a failed validator aren't
+/// helpful, so each type spells out exactly what was wrong and with what
+/// value, qualified by the name of the function the argument belongs to.
+fn validate_arg_value(
+    fn_name: &str,
+    arg_name: &str,
+    ty: &ArgType,
+    value: &str,
+) -> Result<(), String> {
+    match ty {
+        ArgType::Str | ArgType::Path | ArgType::Choice(_) => Ok(()),
+        ArgType::Int => value.parse::<i64>().map(|_| ()).map_err(|_| {
+            format!(
+                "{}: error: {} must be an integer: {}",
+                fn_name, arg_name, value
+            )
+        }),
+        ArgType::Uint => value.parse::<u64>().map(|_| ()).map_err(|_| {
+            format!(
+                "{}: error: {} must be a non-negative integer: {}",
+                fn_name, arg_name, value
+            )
+        }),
+        ArgType::ExistingFile => {
+            if Path::new(value).is_file() {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{}: error: {} must be an existing file: {}",
+                    fn_name, arg_name, value
+                ))
+            }
+        }
+    }
+}
+
+/// Flags and options are passed to the generated function through the
+/// environment rather than as positional arguments, so that `shift`ing off
+/// the fixed positionals isn't disturbed by an argument that might be absent.
+fn env_var_name(name: &str) -> String {
+    name.to_uppercase().replace('-', "_")
+}
+
+/// Quotes a literal value for safe splicing into generated shell source, as
+/// a single-quoted string so it's taken verbatim rather than re-parsed as
+/// shell syntax (parameter/command substitution, globbing, ...).
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn extract_args(
+    arg_matches: &ArgMatches,
+    item_args: Vec<&ItemArg>,
+) -> (Vec<String>, HashMap<String, String>) {
+    let mut args = Vec::new();
+    let mut env = HashMap::new();
+
+    for item_arg in item_args {
+        match &item_arg.kind {
+            ArgKind::Positional(Arity::Variadic) => {
+                if let Some(values) = arg_matches.values_of(item_arg.name) {
+                    args.extend(values.map(str::to_owned));
+                }
+            }
+            ArgKind::Positional(arity) => {
+                let value = match arg_matches.values_of(item_arg.name) {
+                    Some(mut values) => {
+                        let value = values.next().unwrap();
+                        assert!(values.next().is_none());
+
+                        value.to_owned()
+                    }
+                    None => match arity {
+                        Arity::Required => {
+                            unreachable!("clap would have rejected a missing required arg")
+                        }
+                        Arity::Optional { default } => default.clone(),
+                        Arity::Variadic => {
+                            unreachable!("the Variadic arm above already handled this parameter")
+                        }
+                    },
+                };
+
+                args.push(value);
+            }
+            ArgKind::Flag => {
+                env.insert(
+                    env_var_name(item_arg.name),
+                    arg_matches.is_present(item_arg.name).to_string(),
+                );
+            }
+            ArgKind::Option => {
+                if let Some(value) = arg_matches.value_of(item_arg.name) {
+                    env.insert(env_var_name(item_arg.name), value.to_owned());
+                }
+            }
+        }
+    }
+
+    (args, env)
 }
 
 impl<'a> Display for Script<'a> {
@@ -174,9 +456,47 @@ impl<'a> Display for Script<'a> {
     }
 }
 
-#[derive(Error, Debug)]
-#[error("Parse error:\n{0}")]
-pub struct ParseError(String);
+/// A parse failure, pointing at the line and column it occurred on so it can
+/// be rendered as an annotated source snippet rather than a flat message.
+#[derive(Debug)]
+pub struct ParseError {
+    message: String,
+    line: u32,
+    column: usize,
+    source_line: String,
+}
+
+impl ParseError {
+    fn at(source: &str, span: Span, message: impl Into<String>) -> Self {
+        let line = span.location_line();
+        let column = span.get_column();
+        let source_line = source
+            .lines()
+            .nth(line as usize - 1)
+            .unwrap_or("")
+            .to_owned();
+
+        Self {
+            message: message.into(),
+            line,
+            column,
+            source_line,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gutter = format!("{} | ", self.line);
+        let caret = " ".repeat(gutter.len() + self.column.saturating_sub(1));
+
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(f, "{}{}", gutter, self.source_line)?;
+        write!(f, "{}^", caret)
+    }
+}
+
+impl std::error::Error for ParseError {}
 
 fn count_newlines(s: &str) -> usize {
     bytecount::count(s.as_bytes(), b'\n')
@@ -227,6 +547,7 @@ impl<'a> Item<'a> {
 #[derive(Debug)]
 struct FnSignature<'a> {
     name: &'a str,
+    name_span: Span<'a>,
     args: Vec<ItemArg<'a>>,
 }
 
@@ -235,7 +556,43 @@ impl<'a> FnSignature<'a> {
         let mut arg_str = String::new();
 
         for arg in &self.args {
-            arg_str.push_str(&format!("{}=\"$1\"; shift; ", arg.name));
+            match &arg.kind {
+                ArgKind::Positional(Arity::Required) => {
+                    arg_str.push_str(&format!("{}=\"$1\"; shift; ", arg.name));
+                }
+                ArgKind::Positional(Arity::Optional { default }) => {
+                    // The default is assigned first so an absent positional still
+                    // leaves something to `shift` guard against, rather than
+                    // breaking a later required arg's `shift`. It's shell-quoted
+                    // rather than spliced into a double-quoted string, since a
+                    // default containing `$` or `` ` `` would otherwise be
+                    // re-interpreted as live shell syntax instead of a literal.
+                    arg_str.push_str(&format!(
+                        "{name}={default}; if [ \"$#\" -gt 0 ]; then {name}=\"$1\"; shift; fi; ",
+                        name = arg.name,
+                        default = shell_quote(default),
+                    ));
+                }
+                ArgKind::Positional(Arity::Variadic) => {
+                    // The fixed parameters above have already shifted
+                    // themselves off, so whatever's left is exactly this
+                    // parameter's values - nothing to do but leave it in "$@".
+                }
+                ArgKind::Flag => {
+                    arg_str.push_str(&format!(
+                        "{name}=\"${{{env}:-false}}\"; ",
+                        name = arg.name,
+                        env = env_var_name(arg.name),
+                    ));
+                }
+                ArgKind::Option => {
+                    arg_str.push_str(&format!(
+                        "{name}=\"${{{env}:-}}\"; ",
+                        name = arg.name,
+                        env = env_var_name(arg.name),
+                    ));
+                }
+            }
         }
 
         arg_str
@@ -246,6 +603,36 @@ impl<'a> FnSignature<'a> {
 struct ItemArg<'a> {
     name: &'a str,
     description: Description,
+    kind: ArgKind,
+    ty: ArgType,
+}
+
+#[derive(Debug, Clone)]
+enum ArgType {
+    Str,
+    Int,
+    Uint,
+    Path,
+    ExistingFile,
+    Choice(Vec<String>),
+}
+
+#[derive(Debug)]
+enum ArgKind {
+    Positional(Arity),
+    Flag,
+    Option,
+}
+
+#[derive(Debug)]
+enum Arity {
+    Required,
+    Optional {
+        default: String,
+    },
+    /// A trailing positional that collects every remaining argument. Only
+    /// valid as the last parameter of a function.
+    Variadic,
 }
 
 #[derive(Debug)]
@@ -271,3 +658,121 @@ impl<'a> From<&'a Description> for Option<&'a str> {
         (!desc.0.is_empty()).then(|| desc.0.as_str())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choice_typed_argument_is_validated_by_clap() {
+        let script = Script::parse("pub fn deploy(env: choice(dev|prod)) {\n}\n").unwrap();
+        let call = script.parse_args(
+            "sbash",
+            ["sbash", "deploy", "dev"].iter().map(|s| s.to_string()),
+        );
+        assert_eq!(call.args, vec!["dev".to_string()]);
+    }
+
+    #[test]
+    fn shell_quote_neutralises_substitution_syntax() {
+        assert_eq!(shell_quote("dev"), "'dev'");
+        assert_eq!(shell_quote("$(touch /tmp/pwned)"), "'$(touch /tmp/pwned)'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn args_codegen_reads_flags_and_options_from_env() {
+        let sig = FnSignature {
+            name: "backup",
+            name_span: Span::new("backup"),
+            args: vec![
+                ItemArg {
+                    name: "verbose",
+                    description: Description::new(std::iter::empty(), std::iter::empty()),
+                    kind: ArgKind::Flag,
+                    ty: ArgType::Str,
+                },
+                ItemArg {
+                    name: "out",
+                    description: Description::new(std::iter::empty(), std::iter::empty()),
+                    kind: ArgKind::Option,
+                    ty: ArgType::Str,
+                },
+            ],
+        };
+
+        let generated = sig.args();
+        assert!(generated.contains(r#"verbose="${VERBOSE:-false}""#));
+        assert!(generated.contains(r#"out="${OUT:-}""#));
+    }
+
+    #[test]
+    fn args_codegen_leaves_variadic_trailing_args_in_positional_params() {
+        let sig = FnSignature {
+            name: "cp",
+            name_span: Span::new("cp"),
+            args: vec![
+                ItemArg {
+                    name: "dest",
+                    description: Description::new(std::iter::empty(), std::iter::empty()),
+                    kind: ArgKind::Positional(Arity::Required),
+                    ty: ArgType::Str,
+                },
+                ItemArg {
+                    name: "files",
+                    description: Description::new(std::iter::empty(), std::iter::empty()),
+                    kind: ArgKind::Positional(Arity::Variadic),
+                    ty: ArgType::Str,
+                },
+            ],
+        };
+
+        assert_eq!(sig.args(), "dest=\"$1\"; shift; ");
+    }
+
+    #[test]
+    fn pub_main_must_be_the_only_public_function() {
+        let err = Script::parse("pub fn main() {\n}\npub fn other() {\n}\n").unwrap_err();
+        assert!(err.to_string().contains("must be the only public function"));
+    }
+
+    #[test]
+    fn duplicate_alias_names_are_rejected() {
+        let err = Script::parse(
+            "pub fn deploy() {\n}\nalias shortcut => deploy\nalias shortcut => deploy\n",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("duplicate alias name"));
+    }
+
+    #[test]
+    fn alias_cannot_shadow_an_existing_function() {
+        let err = Script::parse("pub fn deploy() {\n}\nalias deploy => deploy\n").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("collides with an existing function"));
+    }
+
+    #[test]
+    fn aliases_are_rejected_when_the_script_has_a_single_pub_main() {
+        let err = Script::parse("pub fn main() {\n}\nalias shortcut => main\n").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("aliases aren't reachable when the script has a single `pub fn main`"));
+    }
+
+    #[test]
+    fn alias_target_must_be_a_known_public_function() {
+        let err = Script::parse("pub fn deploy() {\n}\nalias shortcut => totally-made-up-fn\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("is not a known public function"));
+    }
+
+    #[test]
+    fn required_parameter_cannot_follow_an_optional_one() {
+        let err = Script::parse("pub fn deploy(env = \"dev\", target) {\n}\n").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("may not follow an optional parameter"));
+    }
+}
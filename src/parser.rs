@@ -0,0 +1,381 @@
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while, take_while1},
+    character::complete::{char, line_ending, not_line_ending, space0, space1},
+    combinator::{map, not, opt, peek, recognize, value},
+    multi::{many0, separated_list0},
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    IResult, InputTake,
+};
+use nom_locate::LocatedSpan;
+
+use crate::{ArgKind, ArgType, Arity, Description, FnSignature, Item, ItemArg, ParseError};
+
+pub type Span<'a> = LocatedSpan<&'a str>;
+
+enum Declaration<'a> {
+    Item(Item<'a>),
+    Alias(AliasDecl<'a>),
+}
+
+/// A parsed `alias name => target preset-arg...` declaration, keeping the
+/// spans of its name and target so `Script::parse` can point diagnostics at
+/// the right token instead of the whole declaration.
+pub(crate) struct AliasDecl<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) name_span: Span<'a>,
+    pub(crate) target_span: Span<'a>,
+    pub(crate) expansion: Vec<String>,
+}
+
+/// Parse a whole script into its top level items and command aliases.
+pub fn parse(input: &str) -> Result<(Vec<Item<'_>>, Vec<AliasDecl<'_>>), ParseError> {
+    let span = Span::new(input);
+    let (rest, decls) = many0(alt((
+        map(item, Declaration::Item),
+        map(alias, Declaration::Alias),
+    )))(span)
+    .map_err(|e| to_parse_error(input, e))?;
+    let (rest, _) = blank_lines(rest).map_err(|e| to_parse_error(input, e))?;
+
+    if !rest.fragment().trim().is_empty() {
+        return Err(ParseError::at(
+            input,
+            rest,
+            "expected a function or alias declaration here",
+        ));
+    }
+
+    let mut items = Vec::new();
+    let mut aliases = Vec::new();
+
+    for decl in decls {
+        match decl {
+            Declaration::Item(item) => items.push(item),
+            Declaration::Alias(alias) => aliases.push(alias),
+        }
+    }
+
+    Ok((items, aliases))
+}
+
+/// nom only hands back the span it got stuck at; turn that into a
+/// `ParseError` that can render the offending line.
+fn to_parse_error(source: &str, error: nom::Err<nom::error::Error<Span>>) -> ParseError {
+    match error {
+        nom::Err::Error(e) | nom::Err::Failure(e) => ParseError::at(
+            source,
+            e.input,
+            "expected a function or alias declaration here",
+        ),
+        nom::Err::Incomplete(_) => {
+            ParseError::at(source, Span::new(source), "unexpected end of input")
+        }
+    }
+}
+
+/// `alias name => target preset-arg...`: `name` expands to `target` called
+/// with the preset arguments spliced in ahead of whatever the user typed.
+fn alias(input: Span<'_>) -> IResult<Span<'_>, AliasDecl<'_>> {
+    let (input, _) = blank_lines(input)?;
+    let (input, _) = tag("alias")(input)?;
+    let (input, _) = space1(input)?;
+    let (input, name) = identifier(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = tag("=>")(input)?;
+    let (input, _) = space0(input)?;
+    let (input, target) = identifier(input)?;
+    let (input, preset_args) = many0(preceded(space1, token))(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = opt(line_ending)(input)?;
+
+    let mut expansion = vec![target.fragment().to_string()];
+    expansion.extend(preset_args);
+
+    Ok((
+        input,
+        AliasDecl {
+            name: *name.fragment(),
+            name_span: name,
+            target_span: target,
+            expansion,
+        },
+    ))
+}
+
+fn token(input: Span<'_>) -> IResult<Span<'_>, String> {
+    map(take_while1(|c: char| !c.is_whitespace()), |s: Span| {
+        s.fragment().to_string()
+    })(input)
+}
+
+fn item(input: Span<'_>) -> IResult<Span<'_>, Item<'_>> {
+    let (input, _) = blank_lines(input)?;
+    let (input, doc_lines) = many0(doc_comment_line)(input)?;
+    let (input, is_pub) = map(opt(terminated(tag("pub"), space1)), |p| p.is_some())(input)?;
+    let (input, is_inline) = map(opt(terminated(tag("inline"), space1)), |i| i.is_some())(input)?;
+    let (input, _) = tag("fn")(input)?;
+    let (input, _) = space1(input)?;
+    let (input, name) = identifier(input)?;
+    let (input, _) = space0(input)?;
+    let (input, args) = delimited(char('('), arg_list, char(')'))(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = char('{')(input)?;
+
+    let (input, (body, body_line_number)) = take_body(input)?;
+
+    let (input, _) = char('}')(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = opt(line_ending)(input)?;
+
+    let item = Item {
+        description: Description::new(doc_lines.iter(), std::iter::empty()),
+        is_pub,
+        is_inline,
+        fn_signature: FnSignature {
+            name: *name.fragment(),
+            name_span: name,
+            args,
+        },
+        body: *body.fragment(),
+        body_line_number,
+    };
+
+    Ok((input, item))
+}
+
+fn blank_lines(input: Span<'_>) -> IResult<Span<'_>, ()> {
+    value((), many0(pair(space0, line_ending)))(input)
+}
+
+fn doc_comment_line(input: Span<'_>) -> IResult<Span<'_>, Span<'_>> {
+    let (input, _) = blank_lines(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = char('#')(input)?;
+    let (input, comment) = not_line_ending(input)?;
+    let (input, _) = opt(line_ending)(input)?;
+
+    Ok((input, comment))
+}
+
+fn identifier(input: Span<'_>) -> IResult<Span<'_>, Span<'_>> {
+    recognize(pair(
+        take_while1(|c: char| c.is_alphabetic() || c == '_'),
+        take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+    ))(input)
+}
+
+fn arg_list(input: Span<'_>) -> IResult<Span<'_>, Vec<ItemArg<'_>>> {
+    let (input, _) = blank_lines(input)?;
+    separated_list0(arg_separator, item_arg)(input)
+}
+
+/// Arguments are separated by a comma, a line break, or both - but never by
+/// nothing, since `separated_list0` treats a zero-width separator match as an
+/// infinite loop and errors out instead of just stopping at the last item.
+fn arg_separator(input: Span<'_>) -> IResult<Span<'_>, ()> {
+    value(
+        (),
+        tuple((
+            space0,
+            alt((value((), char(',')), value((), line_ending))),
+            blank_lines,
+            space0,
+        )),
+    )(input)
+}
+
+fn item_arg(input: Span<'_>) -> IResult<Span<'_>, ItemArg<'_>> {
+    let (input, is_named) = map(opt(tag("--")), |dashes| dashes.is_some())(input)?;
+    let (input, name) = identifier(input)?;
+    let (input, _) = space0(input)?;
+    let (input, ty) = map(opt(arg_type), |ty| ty.unwrap_or(ArgType::Str))(input)?;
+    let (input, _) = space0(input)?;
+
+    let (input, kind) = if is_named {
+        let (input, has_value) = map(opt(char('=')), |equals| equals.is_some())(input)?;
+        let kind = if has_value {
+            ArgKind::Option
+        } else {
+            ArgKind::Flag
+        };
+
+        (input, kind)
+    } else {
+        let (input, is_variadic) = map(opt(tag("...")), |dots| dots.is_some())(input)?;
+
+        let (input, arity) = if is_variadic {
+            (input, Arity::Variadic)
+        } else {
+            let (input, default) = opt(preceded(
+                tuple((char('='), space0)),
+                delimited(char('"'), take_while(|c: char| c != '"'), char('"')),
+            ))(input)?;
+
+            let arity = match default {
+                Some(default) => Arity::Optional {
+                    default: default.fragment().to_string(),
+                },
+                None => Arity::Required,
+            };
+
+            (input, arity)
+        };
+
+        (input, ArgKind::Positional(arity))
+    };
+
+    let (input, _) = space0(input)?;
+    let (input, description_comment) =
+        opt(preceded(pair(char('#'), space0), not_line_ending))(input)?;
+
+    Ok((
+        input,
+        ItemArg {
+            name: *name.fragment(),
+            description: Description::new(std::iter::empty(), description_comment.iter()),
+            kind,
+            ty,
+        },
+    ))
+}
+
+fn arg_type(input: Span<'_>) -> IResult<Span<'_>, ArgType> {
+    preceded(
+        pair(char(':'), space0),
+        alt((
+            type_keyword("int", ArgType::Int),
+            type_keyword("uint", ArgType::Uint),
+            type_keyword("existing-file", ArgType::ExistingFile),
+            type_keyword("path", ArgType::Path),
+            type_keyword("str", ArgType::Str),
+            choice_type,
+        )),
+    )(input)
+}
+
+/// Matches a fixed type keyword only when it isn't immediately followed by
+/// more identifier characters, so a typo like `:intx` fails to parse instead
+/// of matching `int` and leaving `x` behind to be misread as a new argument.
+fn type_keyword<'a>(
+    keyword: &'static str,
+    ty: ArgType,
+) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, ArgType> {
+    move |input| {
+        value(
+            ty.clone(),
+            terminated(
+                tag(keyword),
+                peek(not(take_while1(|c: char| {
+                    c.is_alphanumeric() || c == '_' || c == '-'
+                }))),
+            ),
+        )(input)
+    }
+}
+
+fn choice_type(input: Span<'_>) -> IResult<Span<'_>, ArgType> {
+    map(
+        delimited(
+            tag("choice("),
+            separated_list0(char('|'), identifier),
+            char(')'),
+        ),
+        |choices| ArgType::Choice(choices.iter().map(|c| c.fragment().to_string()).collect()),
+    )(input)
+}
+
+/// Consume everything up to (but not including) the `}` that closes the
+/// item's body, tracking the source line the body's first line starts on so
+/// `Item::script` can reproduce it in the generated output.
+fn take_body(input: Span<'_>) -> IResult<Span<'_>, (Span<'_>, usize)> {
+    let (input, _) = opt(line_ending)(input)?;
+    let body_line_number = input.location_line() as usize;
+
+    let mut depth = 0i32;
+    let mut end = 0;
+
+    for (i, c) in input.fragment().char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => {
+                end = i;
+                break;
+            }
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    let (rest, body) = input.take_split(end);
+
+    Ok((rest, (body, body_line_number)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_parse_handles_a_multi_parameter_function() {
+        let script = crate::Script::parse("pub fn deploy(env, target) {\n  :;\n}\n").unwrap();
+        assert_eq!(script.items.len(), 1);
+        assert_eq!(script.items[0].fn_signature.args.len(), 2);
+    }
+
+    #[test]
+    fn alias_captures_its_name_and_target_spans() {
+        let (_, decl) = alias(Span::new("alias deploy-prod => deploy prod --verbose\n")).unwrap();
+        assert_eq!(decl.name, "deploy-prod");
+        assert_eq!(*decl.name_span.fragment(), "deploy-prod");
+        assert_eq!(*decl.target_span.fragment(), "deploy");
+        assert_eq!(decl.expansion, vec!["deploy", "prod", "--verbose"]);
+    }
+
+    #[test]
+    fn variadic_positional_is_parsed() {
+        let (rest, arg) = item_arg(Span::new("files...")).unwrap();
+        assert_eq!(*rest.fragment(), "");
+        assert_eq!(arg.name, "files");
+        assert!(matches!(arg.kind, ArgKind::Positional(Arity::Variadic)));
+    }
+
+    #[test]
+    fn flag_and_option_are_distinguished_by_a_trailing_equals() {
+        let (_, flag) = item_arg(Span::new("--dry-run")).unwrap();
+        assert!(matches!(flag.kind, ArgKind::Flag));
+
+        let (_, option) = item_arg(Span::new("--out =")).unwrap();
+        assert!(matches!(option.kind, ArgKind::Option));
+    }
+
+    #[test]
+    fn arg_type_matches_a_whole_keyword() {
+        let (rest, ty) = arg_type(Span::new(":int")).unwrap();
+        assert!(matches!(ty, ArgType::Int));
+        assert_eq!(*rest.fragment(), "");
+    }
+
+    #[test]
+    fn arg_type_rejects_a_keyword_with_a_trailing_typo() {
+        assert!(arg_type(Span::new(":intx")).is_err());
+    }
+
+    #[test]
+    fn choice_type_collects_its_alternatives() {
+        let (_, ty) = arg_type(Span::new(":choice(dev|prod)")).unwrap();
+        match ty {
+            ArgType::Choice(choices) => assert_eq!(choices, vec!["dev", "prod"]),
+            other => panic!("expected a choice type, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn optional_positional_keeps_its_default() {
+        let (_, arg) = item_arg(Span::new(r#"env = "dev""#)).unwrap();
+        match arg.kind {
+            ArgKind::Positional(Arity::Optional { default }) => assert_eq!(default, "dev"),
+            other => panic!("expected an optional positional, got {:?}", other),
+        }
+    }
+}